@@ -1,12 +1,29 @@
+use std::path::PathBuf;
 use std::process::Command;
 use std::io::{self, Write};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use reqwest::Client;
 use serde_json::json;
 use futures_util::StreamExt;
 use anyhow::Result;
 use dirs;
 
+mod render;
+use render::LineRenderer;
+
+mod session;
+use session::StoredSession;
+
+mod pkg;
+
+const DEFAULT_MODEL: &str = "tinyllama";
+
+const SYSTEM_PROMPT: &str = "You help troubleshoot Linux commands.";
+
+const EXEC_SYSTEM_PROMPT: &str = "You help troubleshoot Linux commands. \
+When the user wants to run something, respond with a single JSON object of the \
+form {\"command\": \"...\", \"explanation\": \"...\"} and nothing else.";
+
 #[derive(Parser)]
 struct Args {
     /// Include system logs
@@ -21,71 +38,252 @@ struct Args {
     #[arg(long)]
     man: Option<String>,
 
-    /// Choose Ollama model
-    #[arg(long, default_value = "tinyllama")]
-    model: String,
+    /// Include installed/available version info for the given package
+    #[arg(long)]
+    pkg: Option<String>,
+
+    /// Choose Ollama model. Defaults to tinyllama, or to the resumed
+    /// session's saved model when `--session` points at an existing one.
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Stay in a REPL, keeping conversation history between turns
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Ask the model for a runnable shell command and offer to execute it
+    #[arg(long)]
+    exec: bool,
+
+    /// Ollama host to connect to
+    #[arg(long, default_value = "localhost")]
+    host: String,
+
+    /// Ollama port to connect to
+    #[arg(long, default_value_t = 11434)]
+    port: u16,
+
+    /// List the models available on the Ollama host and exit
+    #[arg(long)]
+    list_models: bool,
+
+    /// Token budget for gathered context (logs/history/man/pkg), chars/4 heuristic
+    #[arg(long, default_value_t = 2048)]
+    max_context_tokens: usize,
+
+    /// When logs/history don't fit the remaining budget, summarize them via Ollama instead of truncating
+    #[arg(long)]
+    summarize: bool,
+
+    /// Syntax-highlight code blocks and dim prose in the streamed answer
+    #[arg(long)]
+    render: bool,
+
+    /// Persist and resume a named conversation across runs
+    #[arg(long)]
+    session: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage saved sessions
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+}
 
-    let mut context = String::new();
+#[derive(Subcommand)]
+enum SessionsAction {
+    /// List saved sessions
+    List,
+    /// Print a saved session's transcript
+    Show { name: String },
+}
 
-    if args.logs {
-        let logs = std::fs::read_to_string("/var/log/syslog").unwrap_or_else(|_| "Could not read system logs.".into());
-        context.push_str(&format!("\nLogs:\n{}", logs));
-    }
+/// Raw context pulled from disk/the system, before the token budget is applied.
+struct ContextSources {
+    man: Option<(String, String)>,
+    pkg: Option<(String, String)>,
+    logs: Option<String>,
+    history: Option<String>,
+}
 
-    if args.history {
-        if let Some(home) = dirs::home_dir() {
-            let history = std::fs::read_to_string(home.join(".bash_history")).unwrap_or_else(|_| "Could not read bash history.".into());
-            context.push_str(&format!("\nHistory:\n{}", history));
-        }
+fn estimate_tokens(s: &str) -> usize {
+    s.chars().count().div_ceil(4)
+}
+
+/// Keep the last `max_chars` characters of `s`, landing on a line boundary
+/// where possible so the result reads as whole log lines.
+fn tail_chars(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
     }
+    if max_chars == 0 {
+        return String::new();
+    }
+    let start = s.char_indices().map(|(i, _)| i).nth_back(max_chars - 1).unwrap_or(0);
+    match s[start..].find('\n') {
+        Some(newline) => s[start + newline + 1..].to_string(),
+        None => s[start..].to_string(),
+    }
+}
 
-    if let Some(man_cmd) = &args.man {
+fn base_url(args: &Args) -> String {
+    format!("http://{}:{}", args.host, args.port)
+}
+
+/// Resolve which model to use: an explicit `--model` always wins, otherwise
+/// fall back to the model a resumed session was created with, otherwise the
+/// built-in default.
+fn effective_model(args: &Args, stored: Option<&StoredSession>) -> String {
+    args.model
+        .clone()
+        .or_else(|| stored.map(|session| session.model.clone()))
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string())
+}
+
+/// Fetch the model names the Ollama host currently has pulled.
+async fn fetch_models(client: &Client, args: &Args) -> Result<Vec<String>> {
+    let response = client
+        .get(format!("{}/api/tags", base_url(args)))
+        .send()
+        .await?;
+
+    let body: serde_json::Value = response.json().await?;
+    let models = body["models"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|m| m["name"].as_str().map(String::from))
+        .collect();
+
+    Ok(models)
+}
+
+fn gather_context(args: &Args) -> ContextSources {
+    let logs = if args.logs {
+        Some(std::fs::read_to_string("/var/log/syslog").unwrap_or_else(|_| "Could not read system logs.".into()))
+    } else {
+        None
+    };
+
+    let history = if args.history {
+        dirs::home_dir().map(|home| {
+            std::fs::read_to_string(home.join(".bash_history")).unwrap_or_else(|_| "Could not read bash history.".into())
+        })
+    } else {
+        None
+    };
+
+    let man = args.man.as_ref().map(|man_cmd| {
         let output = Command::new("man")
         .arg(man_cmd)
         .output()
         .unwrap_or_else(|_| panic!("Failed to run man {}", man_cmd));
-        let man_page = String::from_utf8_lossy(&output.stdout);
-        context.push_str(&format!("\nMan Page for {}:\n{}", man_cmd, man_page));
+        (man_cmd.clone(), String::from_utf8_lossy(&output.stdout).into_owned())
+    });
+
+    let pkg = args.pkg.as_ref().map(|pkg_name| (pkg_name.clone(), pkg::diagnose(pkg_name)));
+
+    ContextSources { man, pkg, logs, history }
+}
+
+/// Append `section` to `context` if it fits in `budget` tokens, otherwise
+/// tail-truncate it to whatever budget remains. Returns the budget left over.
+fn fit_section(context: &mut String, budget: usize, section: &str) -> usize {
+    let tokens = estimate_tokens(section);
+    if tokens <= budget {
+        context.push_str(section);
+        budget - tokens
+    } else if budget > 0 {
+        context.push_str(&tail_chars(section, budget * 4));
+        0
+    } else {
+        budget
     }
+}
 
-    // Get user input
-    print!("Enter your question or extra info:\n> ");
-    io::stdout().flush()?;
+/// Fit the gathered context sources into `args.max_context_tokens`, using the
+/// priority order the caller always preserves: user input (handled outside
+/// this function), then the man page, then package info, then the most
+/// recent log/history lines. Man and package sections that don't fit are
+/// tail-truncated; logs/history that don't fit are tail-truncated or, with
+/// `--summarize`, replaced by a preliminary summarization request.
+async fn build_context(client: &Client, args: &Args, model: &str, sources: ContextSources) -> Result<String> {
+    let mut context = String::new();
+    let mut budget = args.max_context_tokens;
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    if let Some((man_cmd, man_page)) = &sources.man {
+        let man_section = format!("\nMan Page for {}:\n{}", man_cmd, man_page);
+        budget = fit_section(&mut context, budget, &man_section);
+    }
 
-    let prompt = format!(
-        "You are a friendly Linux assistant. Be concise, clear, step-by-step.\n\nContext:{}\n\nInput:\n{}",
-        context,
-        input.trim()
-    );
+    if let Some((pkg_name, pkg_info)) = &sources.pkg {
+        let pkg_section = format!("\nPackage info for {}:\n{}", pkg_name, pkg_info);
+        budget = fit_section(&mut context, budget, &pkg_section);
+    }
 
-    let client = Client::new();
+    let mut logs_history = String::new();
+    if let Some(logs) = &sources.logs {
+        logs_history.push_str(&format!("\nLogs:\n{}", logs));
+    }
+    if let Some(history) = &sources.history {
+        logs_history.push_str(&format!("\nHistory:\n{}", history));
+    }
+
+    if !logs_history.is_empty() {
+        if estimate_tokens(&logs_history) <= budget {
+            context.push_str(&logs_history);
+        } else if args.summarize {
+            let summary = summarize_text(client, args, model, &logs_history).await?;
+            context.push_str(&format!("\nSummary of logs/history:\n{}", summary));
+        } else {
+            context.push_str(&tail_chars(&logs_history, budget * 4));
+        }
+    }
 
+    Ok(context)
+}
+
+/// Run a one-off chat request asking Ollama to summarize oversized context,
+/// focusing on errors. The reply is collected without being printed.
+async fn summarize_text(client: &Client, args: &Args, model: &str, text: &str) -> Result<String> {
+    let messages = vec![
+        json!({"role": "system", "content": "Summarize the following logs and shell history, focusing on errors and anything actionable. Be concise."}),
+        json!({"role": "user", "content": text}),
+    ];
+
+    stream_chat(client, &base_url(args), model, &messages, false, false).await
+}
+
+/// Send the running message history to Ollama, streaming the reply to stdout
+/// when `print_output` is set, while also returning the fully-assembled content so it can be appended
+/// back into the conversation as an assistant turn.
+async fn stream_chat(client: &Client, base_url: &str, model: &str, messages: &[serde_json::Value], print_output: bool, render: bool) -> Result<String> {
     let body = json!({
-        "model": args.model,
+        "model": model,
         "stream": true,
-        "messages": [
-            {"role": "system", "content": "You help troubleshoot Linux commands."},
-            {"role": "user", "content": prompt}
-        ]
+        "messages": messages,
     });
 
     let response = client
-    .post("http://localhost:11434/api/chat")
+    .post(format!("{}/api/chat", base_url))
     .json(&body)
     .send()
     .await?;
 
     let mut stream = response.bytes_stream();
+    let mut assistant_reply = String::new();
+    let mut renderer = LineRenderer::new(render);
 
-    println!("\nAnswer:\n");
+    if print_output {
+        println!("\nAnswer:\n");
+    }
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
@@ -93,12 +291,423 @@ async fn main() -> Result<()> {
         for line in chunk_str.lines() {
             if let Ok(json_line) = serde_json::from_str::<serde_json::Value>(line) {
                 if let Some(content) = json_line["message"]["content"].as_str() {
-                    print!("{}", content);
-                    io::stdout().flush()?;
+                    if print_output {
+                        renderer.push(content)?;
+                    }
+                    assistant_reply.push_str(content);
                 }
             }
         }
     }
+    if print_output {
+        renderer.finish()?;
+        println!();
+    }
+
+    Ok(assistant_reply)
+}
+
+/// A shell command suggested by the model, parsed out of its reply.
+struct SuggestedCommand {
+    command: String,
+    explanation: String,
+}
+
+/// Pull a `{"command": ..., "explanation": ...}` object out of a reply that
+/// may otherwise contain surrounding prose or markdown fencing.
+fn extract_suggested_command(reply: &str) -> Option<SuggestedCommand> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let candidate = &reply[start..=end];
+
+    let value: serde_json::Value = serde_json::from_str(candidate).ok()?;
+    let command = value.get("command")?.as_str()?.to_string();
+    let explanation = value.get("explanation").and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+    Some(SuggestedCommand { command, explanation })
+}
+
+/// Ask the user to confirm, then run a model-suggested command, piping its
+/// output to the terminal. On failure, the stderr is fed back to the model
+/// as a follow-up so it can propose a fix.
+async fn confirm_and_run(client: &Client, args: &Args, messages: &mut Vec<serde_json::Value>, reply: &str, model: &str) -> Result<()> {
+    let Some(suggestion) = extract_suggested_command(reply) else {
+        return Ok(());
+    };
+
+    println!("\nSuggested command:\n  {}", suggestion.command);
+    if !suggestion.explanation.is_empty() {
+        println!("({})", suggestion.explanation);
+    }
+
+    print!("\nRun this? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut confirm = String::new();
+    io::stdin().read_line(&mut confirm)?;
+    if !confirm.trim().eq_ignore_ascii_case("y") {
+        println!("Not running.");
+        return Ok(());
+    }
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&suggestion.command)
+        .output()?;
+
+    io::stdout().write_all(&output.stdout)?;
+    io::stderr().write_all(&output.stderr)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        messages.push(json!({
+            "role": "user",
+            "content": format!(
+                "The command `{}` failed with exit status {}. stderr:\n{}\n\nSuggest a fix.",
+                suggestion.command,
+                output.status,
+                stderr
+            )
+        }));
+
+        let fix = stream_chat(client, &base_url(args), model, messages, true, args.render).await?;
+        messages.push(json!({"role": "assistant", "content": fix}));
+    }
+
+    Ok(())
+}
+
+async fn run_interactive(
+    client: &Client,
+    args: &Args,
+    context: String,
+    session_path: Option<PathBuf>,
+    stored: Option<StoredSession>,
+    model: String,
+) -> Result<()> {
+    let system_prompt = if args.exec { EXEC_SYSTEM_PROMPT } else { SYSTEM_PROMPT };
+    let system_preamble = json!({"role": "system", "content": system_prompt});
+    let context_message = json!({"role": "user", "content": format!("Context:{}", context)});
+
+    // `/reset` should restore whatever preamble/context was actually injected
+    // for this conversation: a resumed session's own first turns, or this
+    // run's freshly-built ones for a brand-new session. Capture it once so
+    // resetting a resumed session doesn't clobber its original context with
+    // whatever flags happen to be set on this invocation (usually none).
+    let initial_messages = match &stored {
+        Some(session) => session.messages.iter().take(2).cloned().collect(),
+        None => vec![system_preamble, context_message],
+    };
+
+    let mut messages = match stored {
+        Some(session) => session.messages,
+        None => initial_messages.clone(),
+    };
+
+    let persist = |messages: &Vec<serde_json::Value>| -> Result<()> {
+        if let Some(path) = &session_path {
+            session::save(path, &StoredSession { model: model.clone(), messages: messages.clone() })?;
+        }
+        Ok(())
+    };
+
+    loop {
+        print!("\n> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            // EOF
+            break;
+        }
+        let input = input.trim();
+
+        if input.is_empty() {
+            continue;
+        }
+
+        match input {
+            "/exit" => break,
+            "/reset" => {
+                messages = initial_messages.clone();
+                persist(&messages)?;
+                println!("History cleared.");
+                continue;
+            }
+            _ => {}
+        }
+
+        messages.push(json!({"role": "user", "content": input}));
+
+        let reply = stream_chat(client, &base_url(args), &model, &messages, true, args.render).await?;
+        messages.push(json!({"role": "assistant", "content": reply.clone()}));
+
+        if args.exec {
+            confirm_and_run(client, args, &mut messages, &reply, &model).await?;
+        }
+
+        persist(&messages)?;
+    }
 
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(Commands::Sessions { action }) = &args.command {
+        return match action {
+            SessionsAction::List => session::list(),
+            SessionsAction::Show { name } => session::show(name),
+        };
+    }
+
+    let client = Client::new();
+
+    if args.list_models {
+        let models = fetch_models(&client, &args).await?;
+        println!("Available models on {}:", base_url(&args));
+        for model in &models {
+            println!("  {}", model);
+        }
+        return Ok(());
+    }
+
+    let session_path = args.session.as_deref().map(session::session_path).transpose()?;
+    let stored = session_path.as_deref().map(session::load).transpose()?.flatten();
+    let model = effective_model(&args, stored.as_ref());
+
+    let models = fetch_models(&client, &args).await?;
+    if models.is_empty() {
+        eprintln!(
+            "Warning: {} reports no available models; continuing without validating '{}'.",
+            base_url(&args),
+            model
+        );
+    } else if !models.iter().any(|m| m == &model) {
+        anyhow::bail!(
+            "Model '{}' is not available on {}. Available models:\n{}",
+            model,
+            base_url(&args),
+            models.iter().map(|m| format!("  {}", m)).collect::<Vec<_>>().join("\n")
+        );
+    }
+
+    // Gathering context (man/pkg/logs/history, possibly a live summarization
+    // request) is only useful for a brand-new session; a resumed session
+    // already has its context baked into the first messages.
+    let context = if stored.is_none() {
+        build_context(&client, &args, &model, gather_context(&args)).await?
+    } else {
+        String::new()
+    };
+
+    if args.interactive {
+        return run_interactive(&client, &args, context, session_path, stored, model).await;
+    }
+
+    // Get user input
+    print!("Enter your question or extra info:\n> ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    let system_prompt = if args.exec { EXEC_SYSTEM_PROMPT } else { SYSTEM_PROMPT };
+    let mut messages = match stored {
+        // Resuming a session: the context was already embedded when the
+        // session was created, so append only the bare new turn to the
+        // stored history.
+        Some(session) => {
+            let mut messages = session.messages;
+            messages.push(json!({"role": "user", "content": input.trim()}));
+            messages
+        }
+        None => {
+            let prompt = format!(
+                "You are a friendly Linux assistant. Be concise, clear, step-by-step.\n\nContext:{}\n\nInput:\n{}",
+                context,
+                input.trim()
+            );
+            vec![
+                json!({"role": "system", "content": system_prompt}),
+                json!({"role": "user", "content": prompt}),
+            ]
+        }
+    };
+
+    let reply = stream_chat(&client, &base_url(&args), &model, &messages, true, args.render).await?;
+    messages.push(json!({"role": "assistant", "content": reply.clone()}));
+
+    if args.exec {
+        confirm_and_run(&client, &args, &mut messages, &reply, &model).await?;
+    }
+
+    if let Some(path) = &session_path {
+        session::save(path, &StoredSession { model, messages })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod suggested_command_tests {
+    use super::*;
+
+    #[test]
+    fn extracts_command_from_bare_json() {
+        let reply = r#"{"command": "ls -la", "explanation": "list files"}"#;
+        let suggestion = extract_suggested_command(reply).unwrap();
+        assert_eq!(suggestion.command, "ls -la");
+        assert_eq!(suggestion.explanation, "list files");
+    }
+
+    #[test]
+    fn extracts_command_from_surrounding_prose_and_fencing() {
+        let reply = "Sure, try this:\n```json\n{\"command\": \"df -h\", \"explanation\": \"check disk space\"}\n```\nLet me know how it goes.";
+        let suggestion = extract_suggested_command(reply).unwrap();
+        assert_eq!(suggestion.command, "df -h");
+        assert_eq!(suggestion.explanation, "check disk space");
+    }
+
+    #[test]
+    fn defaults_explanation_when_absent() {
+        let reply = r#"{"command": "whoami"}"#;
+        let suggestion = extract_suggested_command(reply).unwrap();
+        assert_eq!(suggestion.command, "whoami");
+        assert_eq!(suggestion.explanation, "");
+    }
+
+    #[test]
+    fn returns_none_when_no_json_object_present() {
+        assert!(extract_suggested_command("just some prose, no braces here").is_none());
+    }
+
+    #[test]
+    fn returns_none_when_json_is_malformed() {
+        assert!(extract_suggested_command("{\"command\": \"ls\", oops}").is_none());
+    }
+
+    #[test]
+    fn returns_none_when_command_field_is_missing() {
+        assert!(extract_suggested_command(r#"{"explanation": "no command given"}"#).is_none());
+    }
+}
+
+#[cfg(test)]
+mod context_budget_tests {
+    use super::*;
+
+    #[test]
+    fn tail_chars_keeps_whole_string_under_budget() {
+        assert_eq!(tail_chars("short", 100), "short");
+    }
+
+    #[test]
+    fn tail_chars_truncates_to_a_line_boundary() {
+        let text = "line one\nline two\nline three";
+        // The last 10 chars are exactly "line three", which already starts a line.
+        assert_eq!(tail_chars(text, 10), "line three");
+    }
+
+    #[test]
+    fn tail_chars_falls_back_to_raw_tail_without_a_newline() {
+        assert_eq!(tail_chars("abcdefghij", 4), "ghij");
+    }
+
+    #[test]
+    fn tail_chars_does_not_panic_on_multi_byte_codepoints() {
+        // Regression: truncating by byte offset instead of char offset could
+        // land mid-codepoint and panic on non-ASCII log/history content.
+        assert_eq!(tail_chars("aaaa😀😀😀😀😀aaaa", 5), "😀aaaa");
+    }
+
+    #[test]
+    fn estimate_tokens_uses_chars_over_four_rounded_up() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn fit_section_keeps_section_whole_when_it_fits() {
+        let mut context = String::new();
+        let remaining = fit_section(&mut context, 100, "hello");
+        assert_eq!(context, "hello");
+        assert_eq!(remaining, 100 - estimate_tokens("hello"));
+    }
+
+    #[test]
+    fn fit_section_truncates_when_over_budget() {
+        let mut context = String::new();
+        let remaining = fit_section(&mut context, 1, "this is way more than one token");
+        assert_eq!(remaining, 0);
+        assert!(context.len() <= 4);
+    }
+
+    #[test]
+    fn fit_section_is_a_no_op_once_budget_is_exhausted() {
+        let mut context = String::from("kept");
+        let remaining = fit_section(&mut context, 0, "dropped");
+        assert_eq!(context, "kept");
+        assert_eq!(remaining, 0);
+    }
+}
+
+#[cfg(test)]
+mod model_resolution_tests {
+    use super::*;
+
+    fn test_args(model: Option<&str>) -> Args {
+        Args {
+            logs: false,
+            history: false,
+            man: None,
+            pkg: None,
+            model: model.map(String::from),
+            interactive: false,
+            exec: false,
+            host: "localhost".to_string(),
+            port: 11434,
+            list_models: false,
+            max_context_tokens: 2048,
+            summarize: false,
+            render: false,
+            session: None,
+            command: None,
+        }
+    }
+
+    fn test_session(model: &str) -> StoredSession {
+        StoredSession { model: model.to_string(), messages: Vec::new() }
+    }
+
+    #[test]
+    fn base_url_combines_host_and_port() {
+        let mut args = test_args(None);
+        args.host = "example.com".to_string();
+        args.port = 8080;
+        assert_eq!(base_url(&args), "http://example.com:8080");
+    }
+
+    #[test]
+    fn effective_model_prefers_explicit_flag_over_stored_session() {
+        let args = test_args(Some("llama2"));
+        let stored = test_session("tinyllama");
+        assert_eq!(effective_model(&args, Some(&stored)), "llama2");
+    }
+
+    #[test]
+    fn effective_model_falls_back_to_stored_session_when_flag_omitted() {
+        let args = test_args(None);
+        let stored = test_session("llama2");
+        assert_eq!(effective_model(&args, Some(&stored)), "llama2");
+    }
+
+    #[test]
+    fn effective_model_falls_back_to_default_with_no_flag_or_session() {
+        let args = test_args(None);
+        assert_eq!(effective_model(&args, None), DEFAULT_MODEL);
+    }
+}
@@ -0,0 +1,42 @@
+use std::process::Command;
+
+fn command_exists(cmd: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(cmd).is_file()))
+        .unwrap_or(false)
+}
+
+fn run(cmd: &str, args: &[&str]) -> String {
+    match Command::new(cmd).args(args).output() {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if !stdout.trim().is_empty() {
+                stdout.trim().to_string()
+            } else {
+                String::from_utf8_lossy(&output.stderr).trim().to_string()
+            }
+        }
+        Err(_) => format!("Could not run `{} {}`.", cmd, args.join(" ")),
+    }
+}
+
+/// Ask the system package manager for a package's installed version and the
+/// version available in its remote index, so the model has concrete state
+/// instead of guessing from prose.
+pub fn diagnose(name: &str) -> String {
+    if command_exists("pacman") {
+        format!(
+            "Installed (pacman -Qi {name}):\n{}\n\nAvailable (pacman -Si {name}):\n{}",
+            run("pacman", &["-Qi", name]),
+            run("pacman", &["-Si", name]),
+        )
+    } else if command_exists("dpkg") || command_exists("apt") {
+        format!(
+            "Installed (dpkg -l {name}):\n{}\n\nAvailable (apt show {name}):\n{}",
+            run("dpkg", &["-l", name]),
+            run("apt", &["show", name]),
+        )
+    } else {
+        "No supported package manager found (tried pacman, apt/dpkg).".to_string()
+    }
+}
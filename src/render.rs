@@ -0,0 +1,96 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+
+fn syntax_set() -> &'static SyntaxSet {
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_nonewlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Buffers streamed chat fragments and renders them line by line once a
+/// newline completes them: fenced code blocks get syntax highlighting via
+/// syntect, prose is dimmed. Falls back to plain passthrough when stdout
+/// isn't a terminal, or when rendering wasn't requested, so piped output
+/// stays clean.
+pub struct LineRenderer {
+    enabled: bool,
+    buffer: String,
+    // Live parse/highlight state for the fenced block we're currently
+    // inside, kept across lines so multi-line constructs (block comments,
+    // multi-line strings) highlight correctly. `None` outside a block.
+    highlighter: Option<HighlightLines<'static>>,
+}
+
+impl LineRenderer {
+    pub fn new(render: bool) -> Self {
+        Self {
+            enabled: render && io::stdout().is_terminal(),
+            buffer: String::new(),
+            highlighter: None,
+        }
+    }
+
+    /// Feed a streamed fragment, flushing every complete line it produces.
+    pub fn push(&mut self, fragment: &str) -> io::Result<()> {
+        if !self.enabled {
+            print!("{}", fragment);
+            return io::stdout().flush();
+        }
+
+        self.buffer.push_str(fragment);
+        while let Some(pos) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=pos).collect();
+            self.render_line(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Flush whatever's left in the buffer once the stream ends.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.enabled {
+            return io::stdout().flush();
+        }
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.render_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn render_line(&mut self, line: &str) -> io::Result<()> {
+        let trimmed = line.trim_end_matches('\n');
+
+        if let Some(lang) = trimmed.trim_start().strip_prefix("```") {
+            self.highlighter = if self.highlighter.is_some() {
+                None
+            } else {
+                let syntax = syntax_set()
+                    .find_syntax_by_token(lang.trim())
+                    .unwrap_or_else(|| syntax_set().find_syntax_plain_text());
+                let theme = &theme_set().themes["base16-ocean.dark"];
+                Some(HighlightLines::new(syntax, theme))
+            };
+            println!("{}", trimmed);
+            return io::stdout().flush();
+        }
+
+        if let Some(highlighter) = &mut self.highlighter {
+            let ranges = highlighter.highlight_line(trimmed, syntax_set()).unwrap_or_default();
+            println!("{}\x1b[0m", as_24_bit_terminal_escaped(&ranges, false));
+        } else {
+            println!("\x1b[2m{}\x1b[0m", trimmed);
+        }
+
+        io::stdout().flush()
+    }
+}
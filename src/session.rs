@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A conversation persisted to disk: the model it was run against plus the
+/// full running message history, so it can be resumed verbatim later.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredSession {
+    pub model: String,
+    pub messages: Vec<serde_json::Value>,
+}
+
+fn sessions_dir() -> Result<PathBuf> {
+    let dir = dirs::data_dir()
+        .context("could not determine a data directory for this platform")?
+        .join("mimir")
+        .join("sessions");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub fn session_path(name: &str) -> Result<PathBuf> {
+    Ok(sessions_dir()?.join(format!("{}.json", name)))
+}
+
+pub fn load(path: &Path) -> Result<Option<StoredSession>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let raw = std::fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&raw)?))
+}
+
+pub fn save(path: &Path, session: &StoredSession) -> Result<()> {
+    let raw = serde_json::to_string_pretty(session)?;
+    std::fs::write(path, raw)?;
+    Ok(())
+}
+
+/// Print the name of every session saved under the sessions directory.
+pub fn list() -> Result<()> {
+    let dir = sessions_dir()?;
+    let mut names: Vec<String> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.path().file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+
+    if names.is_empty() {
+        println!("No saved sessions.");
+    } else {
+        for name in names {
+            println!("{}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a saved session's transcript.
+pub fn show(name: &str) -> Result<()> {
+    let path = session_path(name)?;
+    let Some(session) = load(&path)? else {
+        anyhow::bail!("No saved session named '{}'", name);
+    };
+
+    println!("Model: {}\n", session.model);
+    for message in &session.messages {
+        let role = message["role"].as_str().unwrap_or("?");
+        let content = message["content"].as_str().unwrap_or("");
+        println!("[{}]\n{}\n", role, content);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_returns_none_for_a_missing_path() {
+        let path = std::env::temp_dir().join("mimir-session-tests-missing.json");
+        let _ = std::fs::remove_file(&path);
+        assert!(load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn save_then_load_round_trips_the_session() {
+        let path = std::env::temp_dir().join("mimir-session-tests-roundtrip.json");
+        let session = StoredSession {
+            model: "llama2".to_string(),
+            messages: vec![
+                serde_json::json!({"role": "system", "content": "be concise"}),
+                serde_json::json!({"role": "user", "content": "hello"}),
+            ],
+        };
+
+        save(&path, &session).unwrap();
+        let loaded = load(&path).unwrap().unwrap();
+
+        assert_eq!(loaded.model, session.model);
+        assert_eq!(loaded.messages, session.messages);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}